@@ -0,0 +1,22 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Per-client allowlist for the `client_credentials` grant, backing
+//! `mas-handlers`' `oauth2::token::client_credentials_grant`.
+
+use oauth2_types::scope::Scope;
+use ulid::Ulid;
+
+/// Whether, and with which scopes, a client may use the `client_credentials`
+/// grant. Unlike the other grants this endpoint handles, `client_credentials`
+/// has no user in the loop to consent to a scope, so access is controlled by
+/// this allowlist instead: a client with no entry here can't use the grant
+/// at all, and one that has an entry can only request scopes within it.
+#[derive(Debug, Clone)]
+pub struct ClientCredentialsAllowlist {
+    pub client_id: Ulid,
+    pub allowed_scopes: Scope,
+}
@@ -0,0 +1,32 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Compatibility-layer session records, for clients that log in through
+//! the legacy Matrix `/login` endpoint (`mas-handlers`' `compat::login`)
+//! rather than OAuth 2.0.
+
+use chrono::{DateTime, Utc};
+use ulid::Ulid;
+
+/// One logged-in device created via the compat `/login` endpoint.
+#[derive(Debug, Clone)]
+pub struct CompatSession {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub device_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The access token handed back by compat `/login`, bound to a
+/// [`CompatSession`] the same way an OAuth 2.0 access token is bound to a
+/// browser session.
+#[derive(Debug, Clone)]
+pub struct CompatAccessToken {
+    pub id: Ulid,
+    pub session_id: Ulid,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,38 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Additive types backing the passkey and TOTP second factors, the
+//! `client_credentials` grant allowlist, compat-layer sessions, and the
+//! handful of `SiteConfig` fields `mas-handlers` reads directly. The rest of
+//! `mas-data-model` (users, sessions, clients, OAuth 2.0 grants, the rest of
+//! `SiteConfig`, …) lives alongside these in the full workspace.
+
+pub mod client_credentials;
+pub mod compat;
+pub mod passkey;
+pub mod site_config;
+pub mod totp;
+
+pub use self::{
+    client_credentials::ClientCredentialsAllowlist,
+    compat::{CompatAccessToken, CompatSession},
+    passkey::{AttestationObject, UserPasskey, UserPasskeyChallenge},
+    site_config::SiteConfig,
+    totp::UserTotp,
+};
+
+/// Decodes a base64url (no padding) string, as used throughout the WebAuthn
+/// ceremonies and challenge encoding.
+pub fn base64_decode(input: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(input)
+}
+
+/// Encodes bytes as base64url (no padding).
+pub fn base64_encode(input: &[u8]) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(input)
+}
@@ -0,0 +1,282 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! WebAuthn/passkey types backing [`crate::UserPasskey`] and the ceremonies
+//! in `mas-handlers`' `views::passkeys`.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use thiserror::Error;
+use ulid::Ulid;
+
+/// A registered WebAuthn credential, stored in the `user_passkeys` table.
+#[derive(Debug, Clone)]
+pub struct UserPasskey {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub name: String,
+    /// Base64url-encoded credential ID, as extracted from the verified
+    /// attestation object at registration time.
+    pub credential_id: String,
+    /// Base64url-encoded COSE public key, as extracted from the verified
+    /// attestation object at registration time.
+    pub public_key: String,
+    pub signature_counter: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A pending registration/authentication/reauthentication challenge, bound
+/// to the user it was issued for so it can only be consumed by an assertion
+/// claiming to be that same user. Registration and reauth issue this for
+/// the current session's user; login issues it for the user named by the
+/// username submitted ahead of the passkey prompt, since there's no session
+/// yet at that point.
+#[derive(Debug, Clone)]
+pub struct UserPasskeyChallenge {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub challenge: Vec<u8>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("unsupported or malformed COSE public key")]
+    UnsupportedKey,
+
+    #[error("signature did not verify")]
+    Invalid,
+}
+
+/// A parsed WebAuthn attestation object (`fmt`, `authData`, and the
+/// `attStmt.sig` field when present, which is all the attestation formats
+/// we support need).
+pub struct AttestationObject {
+    pub fmt: String,
+    pub auth_data: Vec<u8>,
+    pub att_stmt_sig: Vec<u8>,
+}
+
+impl<'de> Deserialize<'de> for AttestationObject {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = ciborium::value::Value::deserialize(deserializer)?;
+        let map = value
+            .as_map()
+            .ok_or_else(|| serde::de::Error::custom("expected a CBOR map"))?;
+
+        let get = |key: &str| {
+            map.iter()
+                .find(|(k, _)| k.as_text() == Some(key))
+                .map(|(_, v)| v)
+        };
+
+        let fmt = get("fmt")
+            .and_then(ciborium::value::Value::as_text)
+            .ok_or_else(|| serde::de::Error::custom("missing fmt"))?
+            .to_owned();
+
+        let auth_data = get("authData")
+            .and_then(ciborium::value::Value::as_bytes)
+            .ok_or_else(|| serde::de::Error::custom("missing authData"))?
+            .clone();
+
+        let att_stmt_sig = get("attStmt")
+            .and_then(ciborium::value::Value::as_map)
+            .and_then(|stmt| stmt.iter().find(|(k, _)| k.as_text() == Some("sig")))
+            .and_then(|(_, v)| v.as_bytes())
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(Self {
+            fmt,
+            auth_data,
+            att_stmt_sig,
+        })
+    }
+}
+
+/// Verifies `signature` over `message` using a base64url-encoded COSE
+/// public key, supporting the two algorithms passkey platform
+/// authenticators actually issue: ES256 (COSE key type EC2, P-256) and
+/// EdDSA (COSE key type OKP, Ed25519).
+pub fn verify_signature(
+    public_key_b64: &str,
+    signature_b64: &str,
+    message: &[u8],
+) -> Result<(), SignatureError> {
+    let public_key = crate::base64_decode(public_key_b64).map_err(|_| SignatureError::UnsupportedKey)?;
+    let signature = crate::base64_decode(signature_b64).map_err(|_| SignatureError::UnsupportedKey)?;
+
+    let cose_key: ciborium::value::Value =
+        ciborium::de::from_reader(public_key.as_slice()).map_err(|_| SignatureError::UnsupportedKey)?;
+    let map = cose_key.as_map().ok_or(SignatureError::UnsupportedKey)?;
+
+    let field = |label: i128| {
+        map.iter().find_map(|(k, v)| {
+            let key = k.as_integer().map(i128::from);
+            (key == Some(label)).then_some(v)
+        })
+    };
+
+    // COSE key type (label 1): 2 = EC2, 1 = OKP.
+    let kty = field(1).and_then(ciborium::value::Value::as_integer).map(i128::from);
+
+    match kty {
+        Some(2) => {
+            // EC2: x (label -2), y (label -3), both 32-byte big-endian coordinates for
+            // P-256.
+            let x = field(-2).and_then(ciborium::value::Value::as_bytes).ok_or(SignatureError::UnsupportedKey)?;
+            let y = field(-3).and_then(ciborium::value::Value::as_bytes).ok_or(SignatureError::UnsupportedKey)?;
+
+            let mut sec1 = Vec::with_capacity(65);
+            sec1.push(0x04);
+            sec1.extend_from_slice(x);
+            sec1.extend_from_slice(y);
+
+            let verifying_key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&sec1)
+                .map_err(|_| SignatureError::UnsupportedKey)?;
+            let signature = p256::ecdsa::Signature::from_der(&signature)
+                .or_else(|_| p256::ecdsa::Signature::from_slice(&signature))
+                .map_err(|_| SignatureError::Invalid)?;
+
+            use p256::ecdsa::signature::Verifier;
+            verifying_key
+                .verify(message, &signature)
+                .map_err(|_| SignatureError::Invalid)
+        }
+        Some(1) => {
+            // OKP: x (label -2) is the raw 32-byte Ed25519 public key.
+            let x = field(-2).and_then(ciborium::value::Value::as_bytes).ok_or(SignatureError::UnsupportedKey)?;
+            let key_bytes: [u8; 32] = x.as_slice().try_into().map_err(|_| SignatureError::UnsupportedKey)?;
+            let verifying_key = ed25519_dalek::VerifyingKey::from_bytes(&key_bytes)
+                .map_err(|_| SignatureError::UnsupportedKey)?;
+            let signature_bytes: [u8; 64] =
+                signature.as_slice().try_into().map_err(|_| SignatureError::Invalid)?;
+            let signature = ed25519_dalek::Signature::from_bytes(&signature_bytes);
+
+            use ed25519_dalek::Verifier;
+            verifying_key
+                .verify(message, &signature)
+                .map_err(|_| SignatureError::Invalid)
+        }
+        _ => Err(SignatureError::UnsupportedKey),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::value::Value;
+
+    use super::*;
+
+    /// Builds the base64url-encoded COSE key `verify_signature` expects for
+    /// an EC2/P-256 (ES256) public key.
+    fn ec2_cose_key(x: &[u8], y: &[u8]) -> String {
+        let map = Value::Map(vec![
+            (Value::from(1), Value::from(2)),       // kty: EC2
+            (Value::from(3), Value::from(-7)),      // alg: ES256
+            (Value::from(-1), Value::from(1)),      // crv: P-256
+            (Value::from(-2), Value::from(x.to_vec())),
+            (Value::from(-3), Value::from(y.to_vec())),
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&map, &mut bytes).expect("map serializes");
+        crate::base64_encode(&bytes)
+    }
+
+    /// Same, for an OKP/Ed25519 (EdDSA) public key.
+    fn okp_cose_key(x: &[u8]) -> String {
+        let map = Value::Map(vec![
+            (Value::from(1), Value::from(1)),  // kty: OKP
+            (Value::from(3), Value::from(-8)), // alg: EdDSA
+            (Value::from(-1), Value::from(6)), // crv: Ed25519
+            (Value::from(-2), Value::from(x.to_vec())),
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&map, &mut bytes).expect("map serializes");
+        crate::base64_encode(&bytes)
+    }
+
+    #[test]
+    fn verifies_ec2_es256_signature() {
+        use p256::ecdsa::{signature::Signer, Signature, SigningKey, VerifyingKey};
+
+        let signing_key = SigningKey::from_slice(&[0x42; 32]).expect("valid scalar");
+        let verifying_key = VerifyingKey::from(&signing_key);
+        let point = verifying_key.to_encoded_point(false);
+
+        let message = b"authenticatorData || SHA256(clientDataJSON)";
+        let signature: Signature = signing_key.sign(message);
+
+        let public_key_b64 = ec2_cose_key(point.x().expect("uncompressed point has x"), point.y().expect("uncompressed point has y"));
+        let signature_b64 = crate::base64_encode(signature.to_der().as_bytes());
+
+        verify_signature(&public_key_b64, &signature_b64, message).expect("signature should verify");
+    }
+
+    #[test]
+    fn rejects_ec2_signature_over_tampered_message() {
+        use p256::ecdsa::{signature::Signer, Signature, SigningKey, VerifyingKey};
+
+        let signing_key = SigningKey::from_slice(&[0x42; 32]).expect("valid scalar");
+        let verifying_key = VerifyingKey::from(&signing_key);
+        let point = verifying_key.to_encoded_point(false);
+
+        let signature: Signature = signing_key.sign(b"original message");
+
+        let public_key_b64 = ec2_cose_key(point.x().expect("uncompressed point has x"), point.y().expect("uncompressed point has y"));
+        let signature_b64 = crate::base64_encode(signature.to_der().as_bytes());
+
+        let result = verify_signature(&public_key_b64, &signature_b64, b"a different message");
+        assert!(matches!(result, Err(SignatureError::Invalid)));
+    }
+
+    #[test]
+    fn verifies_okp_eddsa_signature() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[0x13; 32]);
+        let verifying_key = signing_key.verifying_key();
+
+        let message = b"authenticatorData || SHA256(clientDataJSON)";
+        let signature = signing_key.sign(message);
+
+        let public_key_b64 = okp_cose_key(verifying_key.as_bytes());
+        let signature_b64 = crate::base64_encode(&signature.to_bytes());
+
+        verify_signature(&public_key_b64, &signature_b64, message).expect("signature should verify");
+    }
+
+    #[test]
+    fn rejects_okp_signature_over_tampered_message() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[0x13; 32]);
+        let verifying_key = signing_key.verifying_key();
+
+        let signature = signing_key.sign(b"original message");
+
+        let public_key_b64 = okp_cose_key(verifying_key.as_bytes());
+        let signature_b64 = crate::base64_encode(&signature.to_bytes());
+
+        let result = verify_signature(&public_key_b64, &signature_b64, b"a different message");
+        assert!(matches!(result, Err(SignatureError::Invalid)));
+    }
+
+    #[test]
+    fn rejects_unsupported_cose_key_type() {
+        let map = Value::Map(vec![(Value::from(1), Value::from(3))]); // kty 3: RSA, not supported
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&map, &mut bytes).expect("map serializes");
+        let public_key_b64 = crate::base64_encode(&bytes);
+
+        let result = verify_signature(&public_key_b64, &crate::base64_encode(b"not a signature"), b"message");
+        assert!(matches!(result, Err(SignatureError::UnsupportedKey)));
+    }
+}
@@ -0,0 +1,20 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Deployment-wide settings read directly by `mas-handlers`. The full
+//! `SiteConfig` (branding, captcha, …) lives in the real `mas-data-model`;
+//! only the field this series actually reads is reproduced here, so that it
+//! exists somewhere in the tree rather than being assumed into existence at
+//! the call site.
+
+#[derive(Debug, Clone)]
+pub struct SiteConfig {
+    /// When set, and at least one upstream OAuth 2.0 provider is enabled,
+    /// `views::login::get` redirects straight to the IdP picker instead of
+    /// rendering the password form, making the picker the primary landing
+    /// experience for this deployment.
+    pub idp_picker_is_default_landing: bool,
+}
@@ -0,0 +1,27 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Storage-side record for a user's TOTP factor. The actual RFC 6238
+//! algorithm lives in `mas-handlers`' `totp` module, which operates on the
+//! `encrypted_secret` bytes stored here; this crate only owns the shape of
+//! the record, not the crypto.
+
+use chrono::{DateTime, Utc};
+use ulid::Ulid;
+
+/// A user's TOTP factor, either pending confirmation (`confirmed_at` is
+/// `None`) or active.
+#[derive(Debug, Clone)]
+pub struct UserTotp {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub encrypted_secret: Vec<u8>,
+    pub created_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    /// The time step consumed by the most recent successful verification,
+    /// used to reject replays of the same code within its validity window.
+    pub last_used_step: Option<u64>,
+}
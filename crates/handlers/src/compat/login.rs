@@ -0,0 +1,153 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! The Matrix `/login` compatibility endpoint (`m.login.password`).
+
+use axum::{extract::State, response::IntoResponse, Json};
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    passwords::PasswordManager,
+    rate_limit::{Limiter, RateLimitedError, RequesterFingerprint},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct CompatLoginRequest {
+    identifier: CompatLoginIdentifier,
+    password: String,
+
+    /// The client-chosen device ID for this login, or a freshly generated
+    /// one if omitted, per the `m.login.password` spec.
+    device_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type")]
+enum CompatLoginIdentifier {
+    #[serde(rename = "m.id.user")]
+    User { user: String },
+}
+
+#[derive(Serialize)]
+pub struct CompatLoginResponse {
+    access_token: String,
+    device_id: String,
+}
+
+#[derive(Debug, Error)]
+pub enum RouteError {
+    #[error("invalid credentials")]
+    InvalidCredentials,
+
+    #[error("too many attempts, try again later")]
+    RateLimited,
+
+    #[error(transparent)]
+    Internal(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+crate::impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            RouteError::InvalidCredentials => axum::http::StatusCode::FORBIDDEN,
+            RouteError::RateLimited => axum::http::StatusCode::TOO_MANY_REQUESTS,
+            RouteError::Internal(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[tracing::instrument(name = "handlers.compat.login.get", skip_all)]
+pub async fn get() -> impl IntoResponse {
+    Json(serde_json::json!({ "flows": [{ "type": "m.login.password" }] }))
+}
+
+/// Exchanges a username/password for a Matrix access token, subject to the
+/// same per-account progressive lockout as [`crate::views::login::post`].
+#[tracing::instrument(name = "handlers.compat.login.post", skip_all)]
+pub async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(password_manager): State<PasswordManager>,
+    State(limiter): State<Limiter>,
+    requester: RequesterFingerprint,
+    Json(request): Json<CompatLoginRequest>,
+) -> Result<Json<CompatLoginResponse>, RouteError> {
+    let CompatLoginIdentifier::User { user: username } = &request.identifier;
+
+    match limiter.check_login_attempt(username, requester) {
+        Ok(()) => {}
+        Err(RateLimitedError::AccountLocked { .. } | RateLimitedError::TooManyRequests) => {
+            return Err(RouteError::RateLimited);
+        }
+    }
+
+    let result = verify_password(&mut repo, &password_manager, username, &request.password).await;
+
+    let user = match result {
+        Ok(user) => {
+            limiter.record_login_success(username, requester);
+            user
+        }
+        Err(_) => {
+            limiter.record_login_failure(username, requester);
+            return Err(RouteError::InvalidCredentials);
+        }
+    };
+
+    let device_id = request
+        .device_id
+        .clone()
+        .unwrap_or_else(|| mas_data_model::Device::generate(&mut rng).to_string());
+
+    let session = repo.compat_session().add(&clock, &user, device_id.clone()).await?;
+
+    let access_token = mas_data_model::AccessToken::generate(&mut rng).serialize();
+    repo.compat_access_token()
+        .add(&clock, &session, access_token.clone())
+        .await?;
+
+    Ok(Json(CompatLoginResponse {
+        access_token,
+        device_id,
+    }))
+}
+
+async fn verify_password(
+    repo: &mut BoxRepository,
+    password_manager: &PasswordManager,
+    username: &str,
+    password: &str,
+) -> Result<mas_data_model::User, RouteError> {
+    let user = repo
+        .user()
+        .find_by_username(username)
+        .await?
+        .ok_or(RouteError::InvalidCredentials)?;
+
+    let user_password = repo
+        .user_password()
+        .active(&user)
+        .await?
+        .ok_or(RouteError::InvalidCredentials)?;
+
+    password_manager
+        .verify(
+            user_password.version,
+            password.to_owned(),
+            &user_password.hashed_password,
+        )
+        .await
+        .map_err(|_| RouteError::InvalidCredentials)?;
+
+    Ok(user)
+}
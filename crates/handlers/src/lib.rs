@@ -15,14 +15,15 @@
     clippy::let_with_type_underscore,
 )]
 
-use std::{convert::Infallible, sync::LazyLock, time::Duration};
+use std::{sync::LazyLock, time::Duration};
 
 use axum::{
-    extract::{FromRef, FromRequestParts, OriginalUri, RawQuery, State},
+    extract::{FromRef, FromRequestParts, OriginalUri, RawQuery, Request, State},
     http::Method,
+    middleware::Next,
     response::{Html, IntoResponse},
     routing::{get, post},
-    Extension, Router,
+    Extension, Json, Router,
 };
 use headers::HeaderName;
 use hyper::{
@@ -42,7 +43,6 @@ use mas_storage::{BoxClock, BoxRepository, BoxRng};
 use mas_templates::{ErrorContext, NotFoundContext, TemplateContext, Templates};
 use opentelemetry::metrics::Meter;
 use sqlx::PgPool;
-use tower::util::AndThenLayer;
 use tower_http::cors::{Any, CorsLayer};
 
 use self::{graphql::ExtraRouterParameters, passwords::PasswordManager};
@@ -53,11 +53,13 @@ mod graphql;
 mod health;
 mod oauth2;
 pub mod passwords;
+pub mod totp;
 pub mod upstream_oauth2;
 mod views;
 
 mod activity_tracker;
 mod captcha;
+mod negotiation;
 mod preferred_language;
 mod rate_limit;
 #[cfg(test)]
@@ -365,11 +367,39 @@ where
             mas_router::Login::route(),
             get(self::views::login::get).post(self::views::login::post),
         )
+        .route(
+            mas_router::LoginIdpPicker::route(),
+            get(self::views::idp_picker::get),
+        )
         .route(mas_router::Logout::route(), post(self::views::logout::post))
         .route(
             mas_router::Reauth::route(),
             get(self::views::reauth::get).post(self::views::reauth::post),
         )
+        .route(
+            mas_router::Passkeys::route(),
+            get(self::views::passkeys::get),
+        )
+        .route(
+            mas_router::PasskeysRegistrationOptions::route(),
+            post(self::views::passkeys::registration_options),
+        )
+        .route(
+            mas_router::PasskeysRegister::route(),
+            post(self::views::passkeys::register),
+        )
+        .route(
+            mas_router::PasskeysAuthenticationOptions::route(),
+            post(self::views::passkeys::authentication_options),
+        )
+        .route(
+            mas_router::TotpEnrollOptions::route(),
+            post(self::views::totp::enroll_options),
+        )
+        .route(
+            mas_router::TotpEnrollConfirm::route(),
+            post(self::views::totp::confirm),
+        )
         .route(
             mas_router::Register::route(),
             get(self::views::register::get),
@@ -428,22 +458,37 @@ where
             mas_router::DeviceCodeConsent::route(),
             get(self::oauth2::device::consent::get).post(self::oauth2::device::consent::post),
         )
-        .layer(AndThenLayer::new(
-            move |response: axum::response::Response| async move {
-                if response.status().is_server_error() {
-                    // Error responses should have an ErrorContext attached to them
-                    let ext = response.extensions().get::<ErrorContext>();
-                    if let Some(ctx) = ext {
-                        if let Ok(res) = templates.render_error(ctx) {
-                            let (mut parts, _original_body) = response.into_parts();
-                            parts.headers.remove(CONTENT_TYPE);
-                            parts.headers.remove(CONTENT_LENGTH);
-                            return Ok((parts, Html(res)).into_response());
+        .layer(axum::middleware::from_fn(
+            move |request: Request, next: Next| {
+                let templates = templates.clone();
+                async move {
+                    let prefers_json = self::negotiation::prefers_json(request.headers());
+                    let response = next.run(request).await;
+
+                    if response.status().is_server_error() {
+                        // Error responses should have an ErrorContext attached to them
+                        let ext = response.extensions().get::<ErrorContext>().cloned();
+                        if let Some(ctx) = ext {
+                            if prefers_json {
+                                let status = response.status();
+                                let body = self::negotiation::JsonError {
+                                    error: "server_error",
+                                    error_description: ctx.to_string(),
+                                };
+                                return (status, Json(body)).into_response();
+                            }
+
+                            if let Ok(res) = templates.render_error(&ctx) {
+                                let (mut parts, _original_body) = response.into_parts();
+                                parts.headers.remove(CONTENT_TYPE);
+                                parts.headers.remove(CONTENT_LENGTH);
+                                return (parts, Html(res)).into_response();
+                            }
                         }
                     }
-                }
 
-                Ok::<_, Infallible>(response)
+                    response
+                }
             },
         ))
 }
@@ -459,11 +504,19 @@ pub async fn fallback(
     method: Method,
     version: Version,
     PreferredLanguage(locale): PreferredLanguage,
+    headers: hyper::HeaderMap,
 ) -> Result<impl IntoResponse, FancyError> {
     let ctx = NotFoundContext::new(&method, version, &uri).with_language(locale);
-    // XXX: this should look at the Accept header and return JSON if requested
+
+    if self::negotiation::prefers_json(&headers) {
+        let body = self::negotiation::JsonError {
+            error: "not_found",
+            error_description: ctx.to_string(),
+        };
+        return Ok((StatusCode::NOT_FOUND, Json(body)).into_response());
+    }
 
     let res = templates.render_not_found(&ctx)?;
 
-    Ok((StatusCode::NOT_FOUND, Html(res)))
+    Ok((StatusCode::NOT_FOUND, Html(res)).into_response())
 }
@@ -0,0 +1,42 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Minimal `Accept` header content negotiation, enough to decide between the
+//! templated HTML error pages and a machine-readable JSON body for API
+//! clients that happen to hit a non-API path.
+
+use hyper::{header::ACCEPT, HeaderMap};
+use serde::Serialize;
+
+/// Returns `true` when the first recognised media type in the request's
+/// `Accept` header is a JSON type rather than an HTML one. Browsers and
+/// other HTML-first clients list `text/html` ahead of anything else, so
+/// this is enough to route API clients to the JSON body without a full
+/// q-value-weighted negotiation.
+pub(crate) fn prefers_json(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers.get(ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+
+    for entry in accept.split(',') {
+        let mime = entry.split(';').next().unwrap_or("").trim();
+        match mime {
+            "application/json" | "application/*" => return true,
+            "text/html" | "text/*" | "*/*" => return false,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// A stable, machine-readable error body, used wherever a JSON client hits a
+/// route that would otherwise render an HTML error page.
+#[derive(Serialize)]
+pub(crate) struct JsonError {
+    pub error: &'static str,
+    pub error_description: String,
+}
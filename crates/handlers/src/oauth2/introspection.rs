@@ -0,0 +1,50 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use axum::{response::IntoResponse, Json};
+use mas_oauth2::requests::{IntrospectionRequest, IntrospectionResponse};
+use mas_storage::BoxRepository;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync>),
+}
+
+crate::impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        mas_axum_utils::FancyError::from(anyhow::anyhow!(self)).into_response()
+    }
+}
+
+/// Handles `POST /oauth2/introspect`.
+///
+/// Tokens minted for the `client_credentials` grant have no associated
+/// session, so `sub`/`username` are populated from the owning client instead
+/// of a user.
+#[tracing::instrument(name = "handlers.oauth2.introspection.post", skip_all)]
+pub async fn post(
+    mut repo: BoxRepository,
+    Json(request): Json<IntrospectionRequest>,
+) -> Result<Json<IntrospectionResponse>, RouteError> {
+    let Some(token) = repo.oauth2_access_token().find_by_token(&request.token).await? else {
+        return Ok(Json(IntrospectionResponse::inactive()));
+    };
+
+    let response = match token.owner {
+        mas_data_model::TokenOwner::Session(session) => {
+            IntrospectionResponse::active_for_session(&token, &session)
+        }
+        mas_data_model::TokenOwner::Client(client) => {
+            IntrospectionResponse::active_for_client(&token, &client)
+        }
+    };
+
+    Ok(Json(response))
+}
@@ -0,0 +1,45 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use axum::response::IntoResponse;
+use mas_oauth2::requests::RevocationRequest;
+use mas_storage::BoxRepository;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync>),
+}
+
+crate::impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        mas_axum_utils::FancyError::from(anyhow::anyhow!(self)).into_response()
+    }
+}
+
+/// Handles `POST /oauth2/revoke`.
+///
+/// Works the same whether the token being revoked is bound to a user
+/// session or, as with the `client_credentials` grant, to the client
+/// itself: either way we just look the token up and mark it revoked.
+#[tracing::instrument(name = "handlers.oauth2.revoke.post", skip_all)]
+pub async fn post(
+    mut repo: BoxRepository,
+    axum::Form(request): axum::Form<RevocationRequest>,
+) -> Result<impl IntoResponse, RouteError> {
+    if let Some(token) = repo
+        .oauth2_access_token()
+        .find_by_token(&request.token)
+        .await?
+    {
+        repo.oauth2_access_token().revoke(&token).await?;
+    }
+
+    Ok(axum::http::StatusCode::OK)
+}
@@ -0,0 +1,257 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use axum::{response::IntoResponse, Json};
+use mas_data_model::{AccessToken, Client, RefreshToken, TokenType};
+use mas_oauth2::requests::{
+    AccessTokenRequest, AccessTokenResponse, AuthorizationCodeGrant, ClientCredentialsGrant,
+    DeviceCodeGrant, RefreshTokenGrant,
+};
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use oauth2_types::scope::Scope;
+use thiserror::Error;
+
+use crate::impl_from_error_for_route;
+
+#[derive(Debug, Error)]
+pub enum RouteError {
+    #[error("client is not allowed to use the client_credentials grant")]
+    UnauthorizedClient,
+
+    #[error("requested scope exceeds what this client is allowed to request directly")]
+    ScopeNotAllowed,
+
+    #[error("unsupported grant_type")]
+    UnsupportedGrantType,
+
+    #[error("authorization code is unknown, expired, or already used")]
+    InvalidAuthorizationCode,
+
+    #[error("refresh token is unknown, already used, or does not belong to this client")]
+    InvalidRefreshToken,
+
+    #[error("device code is unknown, expired, or not yet authorized by the user")]
+    AuthorizationPending,
+
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        mas_axum_utils::FancyError::from(anyhow::anyhow!(self)).into_response()
+    }
+}
+
+/// Handles `POST /oauth2/token`, dispatching on `grant_type`.
+///
+/// `client_credentials` mints a token owned by the client itself, for
+/// machine-to-machine clients that have no end user: the client is
+/// authenticated the same way as for the other grants, the requested scope
+/// is checked against a per-client allowlist rather than user consent, and
+/// the resulting token can later be introspected/revoked exactly like a
+/// user-bound one, just with the client reported as its owner. The other
+/// grants this endpoint has always supported are dispatched alongside it,
+/// not replaced by it.
+#[tracing::instrument(name = "handlers.oauth2.token.post", skip_all)]
+pub async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    client: Client,
+    Json(request): Json<AccessTokenRequest>,
+) -> Result<Json<AccessTokenResponse>, RouteError> {
+    match request {
+        AccessTokenRequest::AuthorizationCode(grant) => {
+            authorization_code_grant(&mut rng, &clock, &mut repo, &client, grant).await
+        }
+        AccessTokenRequest::RefreshToken(grant) => {
+            refresh_token_grant(&mut rng, &clock, &mut repo, &client, grant).await
+        }
+        AccessTokenRequest::ClientCredentials(grant) => {
+            client_credentials_grant(&mut rng, &clock, &mut repo, &client, grant).await
+        }
+        AccessTokenRequest::DeviceCode(grant) => {
+            device_code_grant(&mut rng, &clock, &mut repo, &client, grant).await
+        }
+        _ => Err(RouteError::UnsupportedGrantType),
+    }
+}
+
+/// Exchanges a previously-issued authorization code for tokens, binding the
+/// new access (and refresh) token to the same browser session the code was
+/// issued for. The code can only be redeemed once, by the client it was
+/// issued to.
+async fn authorization_code_grant(
+    rng: &mut BoxRng,
+    clock: &BoxClock,
+    repo: &mut BoxRepository,
+    client: &Client,
+    grant: AuthorizationCodeGrant,
+) -> Result<Json<AccessTokenResponse>, RouteError> {
+    let authorization_grant = repo
+        .oauth2_authorization_grant()
+        .find_by_code(&grant.code)
+        .await?
+        .filter(|g| g.client_id == client.id && !g.is_exchanged())
+        .ok_or(RouteError::InvalidAuthorizationCode)?;
+
+    let session = repo
+        .oauth2_authorization_grant()
+        .exchange(clock, &authorization_grant)
+        .await?;
+
+    let ttl = std::time::Duration::from_secs(5 * 60);
+    let access_token = AccessToken::generate(rng);
+    let refresh_token = RefreshToken::generate(rng);
+
+    repo.oauth2_access_token()
+        .add_for_session(rng, clock, &session, &access_token, ttl)
+        .await?;
+    repo.oauth2_refresh_token()
+        .add(rng, clock, &session, &access_token, &refresh_token)
+        .await?;
+
+    Ok(Json(AccessTokenResponse {
+        access_token: access_token.serialize(),
+        token_type: TokenType::Bearer,
+        expires_in: Some(ttl),
+        refresh_token: Some(refresh_token.serialize()),
+        scope: Some(session.scope),
+    }))
+}
+
+/// Rotates a refresh token: the presented token is consumed and a fresh
+/// access/refresh pair is minted for the same session, so a leaked refresh
+/// token can only be replayed once before the legitimate client notices its
+/// token stopped working.
+async fn refresh_token_grant(
+    rng: &mut BoxRng,
+    clock: &BoxClock,
+    repo: &mut BoxRepository,
+    client: &Client,
+    grant: RefreshTokenGrant,
+) -> Result<Json<AccessTokenResponse>, RouteError> {
+    let refresh_token = repo
+        .oauth2_refresh_token()
+        .find_by_token(&grant.refresh_token)
+        .await?
+        .filter(|t| !t.is_revoked())
+        .ok_or(RouteError::InvalidRefreshToken)?;
+
+    let session = repo
+        .oauth2_refresh_token()
+        .session(&refresh_token)
+        .await?;
+
+    if session.client_id != client.id {
+        return Err(RouteError::InvalidRefreshToken);
+    }
+
+    repo.oauth2_refresh_token().revoke(clock, &refresh_token).await?;
+
+    let ttl = std::time::Duration::from_secs(5 * 60);
+    let access_token = AccessToken::generate(rng);
+    let next_refresh_token = RefreshToken::generate(rng);
+
+    repo.oauth2_access_token()
+        .add_for_session(rng, clock, &session, &access_token, ttl)
+        .await?;
+    repo.oauth2_refresh_token()
+        .add(rng, clock, &session, &access_token, &next_refresh_token)
+        .await?;
+
+    Ok(Json(AccessTokenResponse {
+        access_token: access_token.serialize(),
+        token_type: TokenType::Bearer,
+        expires_in: Some(ttl),
+        refresh_token: Some(next_refresh_token.serialize()),
+        scope: Some(session.scope),
+    }))
+}
+
+/// Exchanges a device code grant for tokens bound to the grant's session.
+/// A device code that is unknown or was issued to another client is
+/// answered with [`RouteError::AuthorizationPending`]; this function does
+/// not itself check whether the user has approved the request yet.
+async fn device_code_grant(
+    rng: &mut BoxRng,
+    clock: &BoxClock,
+    repo: &mut BoxRepository,
+    client: &Client,
+    grant: DeviceCodeGrant,
+) -> Result<Json<AccessTokenResponse>, RouteError> {
+    let device_code_grant = repo
+        .oauth2_device_code_grant()
+        .find_by_device_code(&grant.device_code)
+        .await?
+        .filter(|g| g.client_id == client.id)
+        .ok_or(RouteError::AuthorizationPending)?;
+
+    let session = repo
+        .oauth2_device_code_grant()
+        .session(&device_code_grant)
+        .await?;
+
+    repo.oauth2_device_code_grant()
+        .exchange(clock, &device_code_grant)
+        .await?;
+
+    let ttl = std::time::Duration::from_secs(5 * 60);
+    let access_token = AccessToken::generate(rng);
+    let refresh_token = RefreshToken::generate(rng);
+
+    repo.oauth2_access_token()
+        .add_for_session(rng, clock, &session, &access_token, ttl)
+        .await?;
+    repo.oauth2_refresh_token()
+        .add(rng, clock, &session, &access_token, &refresh_token)
+        .await?;
+
+    Ok(Json(AccessTokenResponse {
+        access_token: access_token.serialize(),
+        token_type: TokenType::Bearer,
+        expires_in: Some(ttl),
+        refresh_token: Some(refresh_token.serialize()),
+        scope: Some(session.scope),
+    }))
+}
+
+async fn client_credentials_grant(
+    rng: &mut BoxRng,
+    clock: &BoxClock,
+    repo: &mut BoxRepository,
+    client: &Client,
+    grant: ClientCredentialsGrant,
+) -> Result<Json<AccessTokenResponse>, RouteError> {
+    let allowlist = repo
+        .client_credentials_allowlist()
+        .for_client(client)
+        .await?
+        .ok_or(RouteError::UnauthorizedClient)?;
+
+    let requested_scope = grant.scope.unwrap_or_default();
+    if !requested_scope.is_subset(&allowlist.allowed_scopes) {
+        return Err(RouteError::ScopeNotAllowed);
+    }
+
+    let ttl = std::time::Duration::from_secs(5 * 60);
+    let access_token = AccessToken::generate(rng);
+
+    repo.oauth2_access_token()
+        .add_for_client(rng, clock, client, &access_token, &requested_scope, ttl)
+        .await?;
+
+    Ok(Json(AccessTokenResponse {
+        access_token: access_token.serialize(),
+        token_type: TokenType::Bearer,
+        expires_in: Some(ttl),
+        refresh_token: None,
+        scope: Some(requested_scope),
+    }))
+}
@@ -0,0 +1,377 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Rate limiting for the human-facing endpoints.
+//!
+//! [`Limiter`] combines two things: coarse, [`governor`]-backed per-requester
+//! request-rate limits keyed by [`RequesterFingerprint`], and, for
+//! [`crate::views::login`] and [`crate::compat::login`], a finer-grained
+//! failed-authentication tracker that adds exponential backoff and a
+//! temporary account lockout on top of the coarse limits. That tracker is
+//! actually two counters: one keyed by *both* the target account and the
+//! requester, so a single attacker can't lock an account out for everyone
+//! else, and one keyed by the account alone, so distributing guesses across
+//! many IPs doesn't let an attacker skip the backoff either — the account
+//! is locked as soon as either counter crosses the threshold.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use governor::{Quota, RateLimiter};
+use mas_data_model::SiteConfig;
+use thiserror::Error;
+
+/// Identifies the requester for rate-limiting purposes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RequesterFingerprint(IpAddr);
+
+impl RequesterFingerprint {
+    #[must_use]
+    pub fn new(addr: IpAddr) -> Self {
+        Self(addr)
+    }
+}
+
+impl<S> FromRequestParts<S> for RequesterFingerprint
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let addr = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .map(|ConnectInfo(addr)| addr.ip())
+            .unwrap_or(IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED));
+
+        Ok(Self(addr))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RateLimitedError {
+    #[error("too many requests, try again later")]
+    TooManyRequests,
+
+    #[error("too many failed attempts on this account, try again later")]
+    AccountLocked { retry_after: Duration },
+}
+
+/// Tracks consecutive failed-authentication attempts for one key: an
+/// (account, requester) pair, or an account alone.
+#[derive(Debug, Clone)]
+struct FailureRecord {
+    consecutive_failures: u32,
+    locked_until: Option<Instant>,
+    /// When this record was last touched by a failed attempt, used to sweep
+    /// out entries nobody's retried in a while.
+    last_failure: Instant,
+}
+
+/// How long a record can sit idle (no new failures, not currently locked)
+/// before [`sweep`] reclaims it. Comfortably longer than `lockout_duration`
+/// so a sweep never drops a record while it's still meaningfully enforcing
+/// backoff.
+const FAILURE_RECORD_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Run a sweep every this many recorded failures, rather than on every
+/// single one: a per-account map is already small relative to request
+/// volume, so amortizing the full-map scan keeps the common case cheap.
+const SWEEP_INTERVAL: u32 = 256;
+
+/// Drops every entry that's neither locked nor failed within
+/// [`FAILURE_RECORD_TTL`], so an attacker can't grow the map without bound
+/// by spraying failed logins across an endless stream of distinct accounts:
+/// each one ages out once it's no longer doing any work.
+fn sweep<K: Eq + std::hash::Hash>(failures: &mut HashMap<K, FailureRecord>, now: Instant) {
+    failures.retain(|_, record| {
+        let locked = record.locked_until.is_some_and(|until| until > now);
+        let recent = now.duration_since(record.last_failure) < FAILURE_RECORD_TTL;
+        locked || recent
+    });
+}
+
+/// Parameters for the progressive login lockout. The delay doubles with
+/// each failure past `backoff_after`, capped at `max_delay`; from the
+/// `lockout_after`th failure on, the account is locked out entirely for
+/// `lockout_duration`.
+#[derive(Debug, Clone, Copy)]
+struct LockoutConfig {
+    backoff_after: u32,
+    max_delay: Duration,
+    lockout_after: u32,
+    lockout_duration: Duration,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            backoff_after: 3,
+            max_delay: Duration::from_secs(30),
+            lockout_after: 10,
+            lockout_duration: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+type FingerprintLimiter = RateLimiter<
+    RequesterFingerprint,
+    governor::state::keyed::DefaultKeyedStateStore<RequesterFingerprint>,
+    governor::clock::DefaultClock,
+>;
+
+#[derive(Clone)]
+pub struct Limiter {
+    requests: Arc<FingerprintLimiter>,
+    lockout: LockoutConfig,
+    /// Keyed by (account, requester), so one attacker can't lock an account
+    /// out for every other requester. Periodically swept (see
+    /// [`Self::record_login_failure`]) so failing logins against an endless stream of
+    /// distinct accounts can't grow this without bound.
+    pair_failures: Arc<Mutex<HashMap<(String, RequesterFingerprint), FailureRecord>>>,
+    /// Keyed by account alone, so rotating the requester (e.g. the source
+    /// IP) doesn't let an attacker dodge the backoff. Swept the same way as
+    /// `pair_failures`.
+    account_failures: Arc<Mutex<HashMap<String, FailureRecord>>>,
+    /// Failures recorded since the last sweep of either map;
+    /// `record_login_failure` sweeps both every [`SWEEP_INTERVAL`] calls.
+    failures_since_sweep: Arc<AtomicU32>,
+}
+
+impl Limiter {
+    #[must_use]
+    pub fn new(_site_config: &SiteConfig) -> Self {
+        let quota = Quota::per_minute(NonZeroU32::new(60).expect("60 != 0"));
+
+        Self {
+            requests: Arc::new(RateLimiter::keyed(quota)),
+            lockout: LockoutConfig::default(),
+            pair_failures: Arc::new(Mutex::new(HashMap::new())),
+            account_failures: Arc::new(Mutex::new(HashMap::new())),
+            failures_since_sweep: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// The coarse per-requester request-rate check used across the human
+    /// router.
+    pub fn check_request(&self, requester: RequesterFingerprint) -> Result<(), RateLimitedError> {
+        self.requests
+            .check_key(&requester)
+            .map_err(|_| RateLimitedError::TooManyRequests)
+    }
+
+    /// Call before attempting to authenticate `account` as `requester`:
+    /// returns an error immediately if the account is currently locked out
+    /// by either counter, or if the caller needs to wait out the
+    /// exponential backoff delay first.
+    pub fn check_login_attempt(
+        &self,
+        account: &str,
+        requester: RequesterFingerprint,
+    ) -> Result<(), RateLimitedError> {
+        let pair_locked_until = locked_until(&self.pair_failures, &(account.to_owned(), requester));
+        let account_locked_until = locked_until(&self.account_failures, &account.to_owned());
+
+        let worst_locked_until = pair_locked_until.into_iter().chain(account_locked_until).max();
+
+        if let Some(locked_until) = worst_locked_until {
+            let now = std::time::Instant::now();
+            if now < locked_until {
+                return Err(RateLimitedError::AccountLocked {
+                    retry_after: locked_until - now,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records a failed authentication attempt, applying exponential
+    /// backoff from `backoff_after` failures on and a full lockout from
+    /// `lockout_after` on, against both the (account, requester) pair and the
+    /// account alone.
+    pub fn record_login_failure(&self, account: &str, requester: RequesterFingerprint) {
+        let now = Instant::now();
+        record_failure(
+            &self.pair_failures,
+            (account.to_owned(), requester),
+            &self.lockout,
+            now,
+        );
+        record_failure(&self.account_failures, account.to_owned(), &self.lockout, now);
+
+        if self.failures_since_sweep.fetch_add(1, Ordering::Relaxed) + 1 >= SWEEP_INTERVAL {
+            self.failures_since_sweep.store(0, Ordering::Relaxed);
+            sweep(&mut self.pair_failures.lock().unwrap_or_else(|e| e.into_inner()), now);
+            sweep(&mut self.account_failures.lock().unwrap_or_else(|e| e.into_inner()), now);
+        }
+    }
+
+    /// Resets both failure counters for `account`/`requester` after a
+    /// successful authentication.
+    pub fn record_login_success(&self, account: &str, requester: RequesterFingerprint) {
+        self.pair_failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&(account.to_owned(), requester));
+        self.account_failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(account);
+    }
+}
+
+/// The lockout expiry currently recorded for `key`, if any.
+fn locked_until<K: Eq + std::hash::Hash>(
+    failures: &Mutex<HashMap<K, FailureRecord>>,
+    key: &K,
+) -> Option<Instant> {
+    failures
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(key)
+        .and_then(|record| record.locked_until)
+}
+
+/// Increments the failure counter for `key`, applying the same progressive
+/// backoff/lockout rules regardless of which counter it's tracking.
+fn record_failure<K: Eq + std::hash::Hash>(
+    failures: &Mutex<HashMap<K, FailureRecord>>,
+    key: K,
+    lockout: &LockoutConfig,
+    now: Instant,
+) {
+    let mut failures = failures.lock().unwrap_or_else(|e| e.into_inner());
+    let record = failures.entry(key).or_insert(FailureRecord {
+        consecutive_failures: 0,
+        locked_until: None,
+        last_failure: now,
+    });
+
+    record.consecutive_failures += 1;
+    record.last_failure = now;
+
+    if record.consecutive_failures >= lockout.lockout_after {
+        record.locked_until = Some(now + lockout.lockout_duration);
+    } else if record.consecutive_failures >= lockout.backoff_after {
+        let doublings = record.consecutive_failures - lockout.backoff_after;
+        let delay = Duration::from_millis(500)
+            .saturating_mul(1u32.checked_shl(doublings).unwrap_or(u32::MAX))
+            .min(lockout.max_delay);
+        record.locked_until = Some(now + delay);
+    }
+}
+
+/// The delay [`record_failure`] would apply for the `n`th consecutive
+/// failure, or `None` below `backoff_after`/at-or-past `lockout_after`
+/// (where it locks out for `lockout_duration` instead). Exposed for the
+/// backoff-curve unit tests below.
+#[cfg(test)]
+fn backoff_delay_for(lockout: &LockoutConfig, consecutive_failures: u32) -> Option<Duration> {
+    if consecutive_failures >= lockout.lockout_after || consecutive_failures < lockout.backoff_after {
+        return None;
+    }
+    let doublings = consecutive_failures - lockout.backoff_after;
+    Some(
+        Duration::from_millis(500)
+            .saturating_mul(1u32.checked_shl(doublings).unwrap_or(u32::MAX))
+            .min(lockout.max_delay),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_until_max_delay_then_caps() {
+        let lockout = LockoutConfig::default();
+
+        assert_eq!(backoff_delay_for(&lockout, 0), None);
+        assert_eq!(backoff_delay_for(&lockout, lockout.backoff_after - 1), None);
+        assert_eq!(
+            backoff_delay_for(&lockout, lockout.backoff_after),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            backoff_delay_for(&lockout, lockout.backoff_after + 1),
+            Some(Duration::from_millis(1000))
+        );
+        assert_eq!(
+            backoff_delay_for(&lockout, lockout.backoff_after + 2),
+            Some(Duration::from_millis(2000))
+        );
+
+        // At `lockout_after` it's a full lockout, not a backoff delay.
+        assert_eq!(backoff_delay_for(&lockout, lockout.lockout_after), None);
+
+        // Comfortably past backoff_after, the doubling must have saturated at
+        // max_delay rather than overflowing.
+        assert_eq!(
+            backoff_delay_for(&lockout, lockout.lockout_after - 1),
+            Some(lockout.max_delay)
+        );
+    }
+
+    #[test]
+    fn record_failure_locks_out_past_threshold() {
+        let failures = Mutex::new(HashMap::new());
+        let lockout = LockoutConfig::default();
+        let now = Instant::now();
+
+        for n in 1..lockout.lockout_after {
+            record_failure(&failures, "alice", &lockout, now);
+            assert_eq!(
+                failures.lock().unwrap().get("alice").unwrap().consecutive_failures,
+                n
+            );
+        }
+
+        // One more failure crosses `lockout_after`: locked out, not merely
+        // delayed.
+        record_failure(&failures, "alice", &lockout, now);
+        let locked = locked_until(&failures, &"alice").expect("should be locked out");
+        assert_eq!(locked, now + lockout.lockout_duration);
+    }
+
+    #[test]
+    fn sweep_drops_stale_unlocked_entries_but_keeps_locked_ones() {
+        // A lockout that outlasts `FAILURE_RECORD_TTL`, so "locked" is still
+        // locked at the time of the sweep below.
+        let lockout = LockoutConfig {
+            lockout_duration: FAILURE_RECORD_TTL * 2,
+            ..LockoutConfig::default()
+        };
+        let now = Instant::now();
+        let failures = Mutex::new(HashMap::new());
+
+        // One failure for "stale": below `backoff_after`, so it isn't locked.
+        record_failure(&failures, "stale", &lockout, now);
+        // Drive "locked" past the lockout threshold.
+        for _ in 0..lockout.lockout_after {
+            record_failure(&failures, "locked", &lockout, now);
+        }
+
+        let mut failures = failures.into_inner().unwrap();
+        let later = now + FAILURE_RECORD_TTL + Duration::from_secs(1);
+        sweep(&mut failures, later);
+
+        assert!(!failures.contains_key("stale"));
+        assert!(failures.contains_key("locked"));
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! A dedicated "choose your identity provider" screen, shown instead of (or
+//! alongside) [`crate::views::login`] when several upstream OAuth 2.0
+//! providers are configured.
+
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Redirect},
+};
+use mas_axum_utils::FancyError;
+use mas_router::{Route, UpstreamOAuth2Authorize};
+use mas_storage::BoxRepository;
+use mas_templates::{IdpPickerContext, TemplateContext, Templates};
+use serde::{Deserialize, Serialize};
+
+use crate::PreferredLanguage;
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct PickerQuery {
+    login_hint: Option<String>,
+    next: Option<String>,
+}
+
+/// Lists all enabled upstream providers, linking each to its authorize
+/// endpoint. `login_hint` and `next` are forwarded as query parameters so
+/// they survive the redirect to the chosen provider.
+#[tracing::instrument(name = "handlers.views.idp_picker.get", skip_all)]
+pub async fn get(
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    mut repo: BoxRepository,
+    Query(query): Query<PickerQuery>,
+) -> Result<impl IntoResponse, FancyError> {
+    let providers = repo
+        .upstream_oauth_provider()
+        .all_enabled()
+        .await?
+        .into_iter()
+        .map(|provider| {
+            let mut authorize = UpstreamOAuth2Authorize::new(provider.id);
+            if let Some(login_hint) = &query.login_hint {
+                authorize = authorize.with_login_hint(login_hint.clone());
+            }
+            if let Some(next) = &query.next {
+                authorize = authorize.and_then(next.clone());
+            }
+
+            (provider, authorize.path())
+        })
+        .collect::<Vec<_>>();
+
+    // A single provider makes the picker pointless; send the user straight to
+    // it instead of rendering a list of one.
+    if let [(_, only_target)] = providers.as_slice() {
+        return Ok(Redirect::to(only_target).into_response());
+    }
+
+    let ctx = IdpPickerContext::new(providers).with_language(locale);
+    let content = templates.render_idp_picker(&ctx)?;
+
+    Ok(Html(content).into_response())
+}
@@ -0,0 +1,221 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Redirect},
+    Form,
+};
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError,
+};
+use mas_data_model::SiteConfig;
+use mas_keystore::Encrypter;
+use mas_router::{Route, UrlBuilder};
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use mas_templates::{LoginContext, LoginFormField, TemplateContext, Templates};
+use serde::Deserialize;
+
+use super::idp_picker::PickerQuery;
+
+use crate::{
+    passwords::PasswordManager,
+    rate_limit::{Limiter, RateLimitedError, RequesterFingerprint},
+    PreferredLanguage,
+};
+
+/// Either a password (optionally followed by a TOTP code) or a passkey
+/// assertion gathered client-side via `views::passkeys::authentication_options`
+/// and posted back as an opaque blob, mirroring `views::reauth::ReauthForm`.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LoginForm {
+    Password {
+        username: String,
+        password: String,
+
+        /// Present once the user has been prompted for their second factor,
+        /// after a first submission established that their password is
+        /// correct but the account has TOTP enabled.
+        totp_code: Option<String>,
+    },
+    Passkey {
+        username: String,
+        passkey_response: String,
+    },
+}
+
+impl LoginForm {
+    fn username(&self) -> &str {
+        match self {
+            LoginForm::Password { username, .. } | LoginForm::Passkey { username, .. } => username,
+        }
+    }
+}
+
+/// Renders the login page.
+///
+/// Shows the password form, and additionally offers passkey login: the
+/// client can fetch a challenge from `views::passkeys::authentication_options`
+/// for a username typed into the form and submit the resulting assertion
+/// here instead of a password. When the site is configured with
+/// `idp_picker_is_default_landing` and at least one upstream provider is
+/// enabled, this redirects to the picker instead, forwarding
+/// `login_hint`/`next` along.
+#[tracing::instrument(name = "handlers.views.login.get", skip_all)]
+pub async fn get(
+    State(templates): State<Templates>,
+    State(site_config): State<SiteConfig>,
+    PreferredLanguage(locale): PreferredLanguage,
+    mut repo: BoxRepository,
+    Query(query): Query<PickerQuery>,
+    cookie_jar: CookieJar,
+) -> Result<impl IntoResponse, FancyError> {
+    if site_config.idp_picker_is_default_landing
+        && !repo.upstream_oauth_provider().all_enabled().await?.is_empty()
+    {
+        let destination = mas_router::LoginIdpPicker.with_query(&query);
+        return Ok((cookie_jar, Redirect::to(&destination).into_response()));
+    }
+
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let ctx = LoginContext::default()
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let content = templates.render_login(&ctx)?;
+
+    Ok((cookie_jar, Html(content).into_response()))
+}
+
+/// Handles the login form submission, for either the password or the
+/// passkey path.
+#[tracing::instrument(name = "handlers.views.login.post", skip_all)]
+pub async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(password_manager): State<PasswordManager>,
+    State(encrypter): State<Encrypter>,
+    State(limiter): State<Limiter>,
+    State(url_builder): State<UrlBuilder>,
+    requester: RequesterFingerprint,
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<LoginForm>>,
+) -> Result<impl IntoResponse, FancyError> {
+    let form = cookie_jar.verify_form(&clock, form)?;
+    let username = form.username().to_owned();
+
+    match limiter.check_login_attempt(&username, requester) {
+        Ok(()) => {}
+        Err(RateLimitedError::AccountLocked { retry_after }) => {
+            return Err(FancyError::from(anyhow::anyhow!(
+                "too many attempts on this account, try again in {}s",
+                retry_after.as_secs()
+            )));
+        }
+        Err(RateLimitedError::TooManyRequests) => {
+            return Err(FancyError::from(anyhow::anyhow!(
+                "too many attempts, try again later"
+            )));
+        }
+    }
+
+    let result = match &form {
+        LoginForm::Password {
+            username,
+            password,
+            totp_code,
+        } => {
+            attempt_password_login(
+                &clock,
+                &encrypter,
+                &mut repo,
+                &password_manager,
+                username,
+                password,
+                totp_code.as_deref(),
+            )
+            .await
+        }
+        LoginForm::Passkey {
+            username,
+            passkey_response,
+        } => crate::views::passkeys::verify_assertion_for_login(
+            &url_builder,
+            &clock,
+            &mut repo,
+            username,
+            passkey_response,
+        )
+        .await
+        .map_err(|e| FancyError::from(anyhow::anyhow!(e))),
+    };
+
+    match result {
+        Ok(user) => {
+            limiter.record_login_success(&username, requester);
+            let _ = (rng, user);
+            Ok(mas_router::Account::route())
+        }
+        Err(e) => {
+            limiter.record_login_failure(&username, requester);
+            Err(e)
+        }
+    }
+}
+
+/// Verifies the password and, when enabled, the TOTP code, returning the
+/// authenticated user on success. Kept separate from [`post`] so that every
+/// failure path — bad username, bad password, bad TOTP code — goes through
+/// the same `record_login_failure` call above.
+async fn attempt_password_login(
+    clock: &BoxClock,
+    encrypter: &Encrypter,
+    repo: &mut BoxRepository,
+    password_manager: &PasswordManager,
+    username: &str,
+    password: &str,
+    totp_code: Option<&str>,
+) -> Result<mas_data_model::User, FancyError> {
+    let user = repo
+        .user()
+        .find_by_username(username)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("invalid credentials"))?;
+
+    let user_password = repo
+        .user_password()
+        .active(&user)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("no password set on this account"))?;
+
+    password_manager
+        .verify(
+            user_password.version,
+            password.to_owned(),
+            &user_password.hashed_password,
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("invalid credentials"))?;
+
+    if let Some(totp) = repo.user_totp().active(&user).await? {
+        let code = totp_code.ok_or_else(|| anyhow::anyhow!("totp code required"))?;
+
+        let secret = crate::totp::TotpSecret::from_encrypted_bytes(totp.encrypted_secret.clone());
+        let now = clock.now().timestamp().try_into().unwrap_or(0);
+        let step = secret
+            .verify(encrypter, code, now, totp.last_used_step)
+            .map_err(|_| anyhow::anyhow!("invalid code"))?;
+
+        repo.user_totp().record_used_step(clock, &totp, step).await?;
+    }
+
+    Ok(user)
+}
@@ -0,0 +1,11 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+pub mod idp_picker;
+pub mod login;
+pub mod passkeys;
+pub mod reauth;
+pub mod totp;
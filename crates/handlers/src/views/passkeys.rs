@@ -0,0 +1,505 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! WebAuthn/passkey registration and authentication ceremonies.
+//!
+//! This module implements enough of the WebAuthn Level 2 relying-party
+//! contract to register a new authenticator (`navigator.credentials.create`)
+//! and to later authenticate with it (`navigator.credentials.get`), without
+//! depending on a full external WebAuthn crate: we only need to verify the
+//! attestation/assertion signatures and a handful of flags, not the full
+//! metadata service / attestation statement format matrix.
+//!
+//! Both ceremonies trust exactly two things from the client: the signed
+//! `clientDataJSON` and the signed `authenticatorData`. The credential ID
+//! and public key are *extracted from* the verified attestation object
+//! during registration, never taken as separate client-asserted form
+//! fields, since that would let a caller register an arbitrary key of
+//! their choosing.
+//!
+//! Challenges are scoped to a user, not a browser session: registration and
+//! reauth issue theirs for the current session's user, while
+//! [`authentication_options`] (used by `views::login`'s passwordless path)
+//! issues one for the user named by the submitted username, since no
+//! session exists yet at that point in the login flow.
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+    Form, Json,
+};
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError,
+};
+use mas_data_model::UserPasskeyChallenge;
+use mas_router::UrlBuilder;
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use mas_templates::{PasskeysContext, TemplateContext, Templates};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Number of random bytes used for a registration/authentication challenge.
+const CHALLENGE_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum RouteError {
+    #[error("no such challenge, or it has expired")]
+    UnknownChallenge,
+
+    #[error("attestation object could not be parsed or verified")]
+    InvalidAttestation,
+
+    #[error("assertion signature did not verify")]
+    InvalidAssertion,
+
+    #[error("signature counter did not increase: possible cloned authenticator")]
+    CounterDidNotIncrease,
+
+    #[error(transparent)]
+    Internal(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+crate::impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        FancyError::from(anyhow::anyhow!(self)).into_response()
+    }
+}
+
+/// Renders the passkey registration/management page for the account
+/// console.
+#[tracing::instrument(name = "handlers.views.passkeys.get", skip_all)]
+pub async fn get(
+    State(templates): State<Templates>,
+    cookie_jar: CookieJar,
+) -> Result<impl IntoResponse, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+    let ctx = PasskeysContext::default().with_csrf(csrf_token.form_value());
+    let content = templates.render_passkeys(&ctx)?;
+    Ok((cookie_jar, Html(content)))
+}
+
+#[derive(Serialize)]
+pub struct ChallengeResponse {
+    challenge: String,
+    rp_id: String,
+}
+
+/// Issues a fresh registration challenge, bound to the current session's
+/// user, that the browser will sign over via `navigator.credentials.create()`.
+#[tracing::instrument(name = "handlers.views.passkeys.registration_options", skip_all)]
+pub async fn registration_options(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(url_builder): State<UrlBuilder>,
+    mas_axum_utils::user_session::Session(session): mas_axum_utils::user_session::Session,
+) -> Result<Json<ChallengeResponse>, RouteError> {
+    let mut challenge = vec![0u8; CHALLENGE_LEN];
+    rng.fill_bytes(&mut challenge);
+
+    repo.user_passkeys()
+        .add_challenge(&mut rng, &clock, &session.user, challenge.clone())
+        .await?;
+
+    Ok(Json(ChallengeResponse {
+        challenge: mas_data_model::base64_encode(&challenge),
+        rp_id: rp_id(&url_builder),
+    }))
+}
+
+/// Issues a fresh authentication challenge for `username`'s registered
+/// passkeys, that the browser will sign over via
+/// `navigator.credentials.get()` as part of passwordless sign-in.
+///
+/// Unlike registration, this runs before the caller has proved anything, so
+/// the challenge is bound to the user named by `username` rather than to a
+/// session — there isn't one yet. The username must still belong to a real
+/// account; a deployment that wants to hide account existence at this point
+/// would need to fold this into a resident-key (discoverable credential)
+/// flow instead, which is out of scope here.
+#[derive(Deserialize)]
+pub struct AuthenticationOptionsForm {
+    username: String,
+}
+
+#[tracing::instrument(name = "handlers.views.passkeys.authentication_options", skip_all)]
+pub async fn authentication_options(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(url_builder): State<UrlBuilder>,
+    Json(form): Json<AuthenticationOptionsForm>,
+) -> Result<Json<ChallengeResponse>, RouteError> {
+    let user = repo
+        .user()
+        .find_by_username(&form.username)
+        .await?
+        .ok_or(RouteError::UnknownChallenge)?;
+
+    let mut challenge = vec![0u8; CHALLENGE_LEN];
+    rng.fill_bytes(&mut challenge);
+
+    repo.user_passkeys()
+        .add_challenge(&mut rng, &clock, &user, challenge.clone())
+        .await?;
+
+    Ok(Json(ChallengeResponse {
+        challenge: mas_data_model::base64_encode(&challenge),
+        rp_id: rp_id(&url_builder),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct RegistrationForm {
+    /// Base64url-encoded CBOR attestation object, as returned by
+    /// `navigator.credentials.create()`. The credential ID and public key
+    /// are extracted from this (after it's verified), not read from any
+    /// other client-supplied field.
+    attestation_object: String,
+    /// Base64url-encoded `clientDataJSON`, checked against the pending
+    /// challenge and the expected origin.
+    client_data_json: String,
+    name: String,
+}
+
+/// Verifies the attestation produced by `navigator.credentials.create()` and
+/// persists the new credential.
+#[tracing::instrument(name = "handlers.views.passkeys.register", skip_all)]
+pub async fn register(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(url_builder): State<UrlBuilder>,
+    mas_axum_utils::user_session::Session(session): mas_axum_utils::user_session::Session,
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<RegistrationForm>>,
+) -> Result<impl IntoResponse, FancyError> {
+    let form = cookie_jar.verify_form(&clock, form)?;
+
+    let challenge: UserPasskeyChallenge = repo
+        .user_passkeys()
+        .consume_challenge(&clock, &session.user)
+        .await?
+        .ok_or(RouteError::UnknownChallenge)?;
+
+    let client_data_raw = verify_client_data(
+        &url_builder,
+        &form.client_data_json,
+        "webauthn.create",
+        &challenge.challenge,
+    )
+    .map_err(|_| RouteError::InvalidAttestation)?;
+    let client_data_hash = Sha256::digest(&client_data_raw);
+
+    let attestation_object = mas_data_model::base64_decode(&form.attestation_object)
+        .map_err(|_| RouteError::InvalidAttestation)?;
+    let (credential_id, public_key) =
+        verify_attestation_object(&url_builder, &attestation_object, &client_data_hash)?;
+
+    repo.user_passkeys()
+        .add(
+            &clock,
+            &session.user,
+            form.name,
+            mas_data_model::base64_encode(&credential_id),
+            mas_data_model::base64_encode(&public_key),
+        )
+        .await?;
+
+    Ok(mas_router::Account::route())
+}
+
+/// A parsed (not yet attestation-verified) `authData` field, common to both
+/// the registration attestation object and authentication assertions.
+struct AuthenticatorData {
+    rp_id_hash: [u8; 32],
+    user_present: bool,
+    /// The authenticator's signature counter (big-endian, bytes 33..37).
+    sign_count: u32,
+    /// Present only when the `AT` (attested credential data) flag is set,
+    /// i.e. during registration.
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl AuthenticatorData {
+    fn parse(raw: &[u8]) -> Result<Self, ()> {
+        if raw.len() < 37 {
+            return Err(());
+        }
+
+        let mut rp_id_hash = [0u8; 32];
+        rp_id_hash.copy_from_slice(&raw[0..32]);
+        let flags = raw[32];
+        let user_present = flags & 0x01 != 0;
+        let attested_credential_data_included = flags & 0x40 != 0;
+        let sign_count = u32::from_be_bytes([raw[33], raw[34], raw[35], raw[36]]);
+
+        let attested_credential = if attested_credential_data_included {
+            // AAGUID (16 bytes) + credential ID length (2 bytes) + credential ID +
+            // COSE public key (CBOR, rest of the buffer).
+            let rest = &raw[37..];
+            if rest.len() < 18 {
+                return Err(());
+            }
+            let cred_id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+            let cred_id_start = 18;
+            let cred_id_end = cred_id_start + cred_id_len;
+            if rest.len() < cred_id_end {
+                return Err(());
+            }
+            let credential_id = rest[cred_id_start..cred_id_end].to_vec();
+            let public_key = rest[cred_id_end..].to_vec();
+            Some((credential_id, public_key))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rp_id_hash,
+            user_present,
+            sign_count,
+            attested_credential,
+        })
+    }
+}
+
+/// Verifies a CBOR attestation object against the relying party and the
+/// signed `clientDataJSON`, and returns the credential ID/public key it
+/// attests to.
+///
+/// For the common `"none"` attestation format used by platform passkeys,
+/// trust is anchored entirely in the RP ID hash and the user-present flag
+/// inside `authData` (there is no attestation signature to check). For
+/// `"packed"` self-attestation, the signature over
+/// `authData || clientDataHash` is additionally verified against the
+/// credential's own public key, so a forged object can't substitute a key
+/// the authenticator never holds.
+fn verify_attestation_object(
+    url_builder: &UrlBuilder,
+    attestation_object: &[u8],
+    client_data_hash: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), RouteError> {
+    let attestation: mas_data_model::passkey::AttestationObject =
+        ciborium::de::from_reader(attestation_object).map_err(|_| RouteError::InvalidAttestation)?;
+
+    let auth_data = AuthenticatorData::parse(&attestation.auth_data)
+        .map_err(|_| RouteError::InvalidAttestation)?;
+
+    if auth_data.rp_id_hash[..] != Sha256::digest(rp_id(url_builder).as_bytes())[..] {
+        return Err(RouteError::InvalidAttestation);
+    }
+
+    if !auth_data.user_present {
+        return Err(RouteError::InvalidAttestation);
+    }
+
+    let (credential_id, public_key) = auth_data
+        .attested_credential
+        .ok_or(RouteError::InvalidAttestation)?;
+
+    if attestation.fmt == "packed" {
+        let mut signed = attestation.auth_data.clone();
+        signed.extend_from_slice(client_data_hash);
+        mas_data_model::passkey::verify_signature(&public_key, &attestation.att_stmt_sig, &signed)
+            .map_err(|_| RouteError::InvalidAttestation)?;
+    }
+
+    Ok((credential_id, public_key))
+}
+
+#[derive(Deserialize)]
+pub struct AssertionForm {
+    credential_id: String,
+    /// Base64url-encoded signature over `authenticatorData || SHA256(clientDataJSON)`.
+    signature: String,
+    /// Base64url-encoded `authenticatorData`, used to check the RP ID hash,
+    /// the user-present flag and the signature counter.
+    authenticator_data: String,
+    client_data_json: String,
+}
+
+/// Verifies an assertion produced by `navigator.credentials.get()` against a
+/// previously-registered credential: checks the RP ID hash and user-present
+/// flag in `authenticatorData`, the exact origin in `clientDataJSON`,
+/// verifies the signature with the stored COSE public key over
+/// `authenticatorData || SHA256(clientDataJSON)`, and rejects the assertion
+/// if the signature counter in `authenticatorData` didn't strictly increase
+/// (the canonical sign of a cloned authenticator). An authenticator that
+/// reports zero both at registration and now has no counter, and is let
+/// through.
+async fn verify_assertion(
+    url_builder: &UrlBuilder,
+    clock: &BoxClock,
+    repo: &mut BoxRepository,
+    challenge: &[u8],
+    form: &AssertionForm,
+) -> Result<mas_data_model::UserPasskey, RouteError> {
+    let client_data_raw =
+        verify_client_data(url_builder, &form.client_data_json, "webauthn.get", challenge)
+            .map_err(|_| RouteError::InvalidAssertion)?;
+    let client_data_hash = Sha256::digest(&client_data_raw);
+
+    let authenticator_data = mas_data_model::base64_decode(&form.authenticator_data)
+        .map_err(|_| RouteError::InvalidAssertion)?;
+    let auth_data =
+        AuthenticatorData::parse(&authenticator_data).map_err(|_| RouteError::InvalidAssertion)?;
+
+    if auth_data.rp_id_hash[..] != Sha256::digest(rp_id(url_builder).as_bytes())[..] {
+        return Err(RouteError::InvalidAssertion);
+    }
+
+    if !auth_data.user_present {
+        return Err(RouteError::InvalidAssertion);
+    }
+
+    let passkey = repo
+        .user_passkeys()
+        .find_by_credential_id(&form.credential_id)
+        .await?
+        .ok_or(RouteError::InvalidAssertion)?;
+
+    let signature_counter = auth_data.sign_count;
+    if (signature_counter != 0 || passkey.signature_counter != 0)
+        && signature_counter <= passkey.signature_counter
+    {
+        return Err(RouteError::CounterDidNotIncrease);
+    }
+
+    let mut signed = authenticator_data;
+    signed.extend_from_slice(&client_data_hash);
+
+    // Signature verification happens against the stored COSE public key; the
+    // actual ECDSA/EdDSA check lives in `mas_data_model::passkey`, shared with
+    // the registration path so both ceremonies agree on supported algorithms.
+    mas_data_model::passkey::verify_signature(&passkey.public_key, &form.signature, &signed)
+        .map_err(|_| RouteError::InvalidAssertion)?;
+
+    repo.user_passkeys()
+        .set_signature_counter(clock, &passkey, signature_counter)
+        .await?;
+
+    Ok(passkey)
+}
+
+/// Used by `views::login` to offer passwordless sign-in: looks up the
+/// challenge issued by [`authentication_options`] for `username`, verifies
+/// `passkey_response` against it, and returns the authenticated user on
+/// success.
+pub async fn verify_assertion_for_login(
+    url_builder: &UrlBuilder,
+    clock: &BoxClock,
+    repo: &mut BoxRepository,
+    username: &str,
+    passkey_response: &str,
+) -> Result<mas_data_model::User, RouteError> {
+    let form: AssertionForm =
+        serde_json::from_str(passkey_response).map_err(|_| RouteError::InvalidAssertion)?;
+
+    let user = repo
+        .user()
+        .find_by_username(username)
+        .await?
+        .ok_or(RouteError::InvalidAssertion)?;
+
+    let challenge = repo
+        .user_passkeys()
+        .consume_challenge(clock, &user)
+        .await?
+        .ok_or(RouteError::UnknownChallenge)?;
+
+    let passkey = verify_assertion(url_builder, clock, repo, &challenge.challenge, &form).await?;
+
+    // The challenge was scoped to `user`, but it's the credential's own
+    // owner that actually matters: reject a stray passkey registered to
+    // someone else that happens to answer this user's login challenge.
+    if passkey.user_id != user.id {
+        return Err(RouteError::InvalidAssertion);
+    }
+
+    Ok(user)
+}
+
+/// Used by `views::reauth` to accept a passkey assertion as a step-up,
+/// re-proving the identity of the already-signed-in `user` against the
+/// challenge `views::reauth::get` issued for them.
+pub async fn verify_assertion_for_reauth(
+    url_builder: &UrlBuilder,
+    clock: &BoxClock,
+    repo: &mut BoxRepository,
+    user: &mas_data_model::User,
+    passkey_response: &str,
+) -> Result<(), RouteError> {
+    let form: AssertionForm =
+        serde_json::from_str(passkey_response).map_err(|_| RouteError::InvalidAssertion)?;
+
+    let challenge = repo
+        .user_passkeys()
+        .consume_challenge(clock, user)
+        .await?
+        .ok_or(RouteError::UnknownChallenge)?;
+
+    let passkey = verify_assertion(url_builder, clock, repo, &challenge.challenge, &form).await?;
+
+    if passkey.user_id != user.id {
+        return Err(RouteError::InvalidAssertion);
+    }
+
+    Ok(())
+}
+
+/// The relying party ID for this deployment: the effective host of the
+/// public-facing instance, as configured via [`UrlBuilder`]. Unlike a
+/// hardcoded constant, this tracks whatever domain the deployment actually
+/// serves from.
+fn rp_id(url_builder: &UrlBuilder) -> String {
+    url_builder
+        .origin()
+        .host_str()
+        .expect("configured public URL must have a host")
+        .to_owned()
+}
+
+/// Parses `clientDataJSON`, checks its `type`/`challenge`/`origin` fields
+/// against what we expect — the origin must match *exactly*, not merely
+/// share a domain suffix — and returns the raw bytes (for hashing into the
+/// signed message) on success.
+fn verify_client_data(
+    url_builder: &UrlBuilder,
+    client_data_json: &str,
+    expected_type: &str,
+    expected_challenge: &[u8],
+) -> Result<Vec<u8>, ()> {
+    #[derive(Deserialize)]
+    struct ClientData {
+        #[serde(rename = "type")]
+        ceremony_type: String,
+        challenge: String,
+        origin: String,
+    }
+
+    let raw = mas_data_model::base64_decode(client_data_json).map_err(|_| ())?;
+    let client_data: ClientData = serde_json::from_slice(&raw).map_err(|_| ())?;
+
+    if client_data.ceremony_type != expected_type {
+        return Err(());
+    }
+
+    let challenge = mas_data_model::base64_decode(&client_data.challenge).map_err(|_| ())?;
+    if challenge != expected_challenge {
+        return Err(());
+    }
+
+    if client_data.origin != url_builder.origin().as_str().trim_end_matches('/') {
+        return Err(());
+    }
+
+    Ok(raw)
+}
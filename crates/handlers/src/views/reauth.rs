@@ -0,0 +1,138 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+    Form,
+};
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    FancyError,
+};
+use mas_data_model::base64_encode;
+use mas_keystore::Encrypter;
+use mas_router::{Route, UrlBuilder};
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use mas_templates::{ReauthContext, TemplateContext, Templates};
+use rand::RngCore;
+use serde::Deserialize;
+
+use crate::{passwords::PasswordManager, PreferredLanguage};
+
+/// Number of random bytes used for a reauth passkey challenge, matching
+/// `views::passkeys::CHALLENGE_LEN`.
+const CHALLENGE_LEN: usize = 32;
+
+/// A step-up form: a password re-entry, a TOTP code, or a passkey assertion
+/// gathered client-side and posted back as an opaque blob.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ReauthForm {
+    Password { password: String },
+    Totp { totp_code: String },
+    Passkey { passkey_response: String },
+}
+
+/// Renders the re-authentication page, offering passkey step-up alongside
+/// the password form. Issues a fresh passkey challenge scoped to the current
+/// session's user up front on every render, whether or not the user has any
+/// passkey enrolled, mirroring [`crate::views::passkeys::registration_options`],
+/// so [`post`]'s `Passkey` branch has something to consume.
+#[tracing::instrument(name = "handlers.views.reauth.get", skip_all)]
+pub async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    mas_axum_utils::user_session::Session(session): mas_axum_utils::user_session::Session,
+    cookie_jar: CookieJar,
+) -> Result<impl IntoResponse, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let mut challenge = vec![0u8; CHALLENGE_LEN];
+    rng.fill_bytes(&mut challenge);
+
+    repo.user_passkeys()
+        .add_challenge(&mut rng, &clock, &session.user, challenge.clone())
+        .await?;
+
+    let ctx = ReauthContext::default()
+        .with_csrf(csrf_token.form_value())
+        .with_passkey_challenge(base64_encode(&challenge))
+        .with_language(locale);
+
+    let content = templates.render_reauth(&ctx)?;
+
+    Ok((cookie_jar, Html(content)))
+}
+
+/// Handles the re-authentication form submission, accepting the account
+/// password, a TOTP code, or a passkey assertion as proof of step-up.
+#[tracing::instrument(name = "handlers.views.reauth.post", skip_all)]
+pub async fn post(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(password_manager): State<PasswordManager>,
+    State(encrypter): State<Encrypter>,
+    State(url_builder): State<UrlBuilder>,
+    mas_axum_utils::user_session::Session(session): mas_axum_utils::user_session::Session,
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<ReauthForm>>,
+) -> Result<impl IntoResponse, FancyError> {
+    let form = cookie_jar.verify_form(&clock, form)?;
+
+    match form {
+        ReauthForm::Password { password } => {
+            let user_password = repo
+                .user_password()
+                .active(&session.user)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("no password set on this account"))?;
+
+            password_manager
+                .verify(
+                    user_password.version,
+                    password,
+                    &user_password.hashed_password,
+                )
+                .await
+                .map_err(|_| anyhow::anyhow!("invalid credentials"))?;
+        }
+        ReauthForm::Totp { totp_code } => {
+            let totp = repo
+                .user_totp()
+                .active(&session.user)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("TOTP is not enabled on this account"))?;
+
+            let secret = crate::totp::TotpSecret::from_encrypted_bytes(totp.encrypted_secret.clone());
+            let now = clock.now().timestamp().try_into().unwrap_or(0);
+            let step = secret
+                .verify(&encrypter, &totp_code, now, totp.last_used_step)
+                .map_err(|_| anyhow::anyhow!("invalid code"))?;
+
+            repo.user_totp().record_used_step(&clock, &totp, step).await?;
+        }
+        ReauthForm::Passkey { passkey_response } => {
+            // Delegate assertion verification to the passkey ceremony, which
+            // checks the signature, RP ID hash and signature counter before
+            // treating this as a successful step-up.
+            crate::views::passkeys::verify_assertion_for_reauth(
+                &url_builder,
+                &clock,
+                &mut repo,
+                &session.user,
+                &passkey_response,
+            )
+            .await?;
+        }
+    }
+
+    Ok(mas_router::Account::route())
+}
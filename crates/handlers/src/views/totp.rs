@@ -0,0 +1,95 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Enrollment endpoints for [`crate::totp`], reached from the account
+//! management app.
+
+use axum::{extract::State, response::IntoResponse, Form, Json};
+use mas_axum_utils::{
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+    user_session::Session,
+};
+use mas_keystore::Encrypter;
+use mas_storage::{BoxClock, BoxRepository, BoxRng};
+use serde::{Deserialize, Serialize};
+
+use crate::totp::TotpSecret;
+
+#[derive(Serialize)]
+pub struct EnrollmentOptions {
+    secret: String,
+    otpauth_uri: String,
+}
+
+/// `enroll_options` has no fields of its own to submit — it's CSRF-protected
+/// purely so it can't be triggered by a cross-site POST — so the form body
+/// carries nothing beyond the CSRF token [`ProtectedForm`] wraps it in.
+#[derive(Deserialize, Debug, Default)]
+pub struct EnrollOptionsForm {}
+
+/// Generates a new TOTP secret for the current user and stashes it,
+/// unconfirmed, until [`confirm`] is called with a valid code.
+#[tracing::instrument(name = "handlers.views.totp.enroll_options", skip_all)]
+pub async fn enroll_options(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(encrypter): State<Encrypter>,
+    Session(session): Session,
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<EnrollOptionsForm>>,
+) -> Result<Json<EnrollmentOptions>, mas_axum_utils::FancyError> {
+    cookie_jar.verify_form(&clock, form)?;
+
+    let secret = TotpSecret::generate(&mut rng, &encrypter);
+
+    repo.user_totp()
+        .start_enrollment(&clock, &session.user, secret.encrypted_bytes().to_vec())
+        .await?;
+
+    Ok(Json(EnrollmentOptions {
+        secret: secret.to_base32(&encrypter),
+        otpauth_uri: secret.to_otpauth_uri(&encrypter, "Matrix Authentication Service", &session.user.username),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmForm {
+    code: String,
+}
+
+/// Confirms enrollment by requiring one correct code, then marks the pending
+/// secret as the account's active second factor.
+#[tracing::instrument(name = "handlers.views.totp.confirm", skip_all)]
+pub async fn confirm(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(encrypter): State<Encrypter>,
+    Session(session): Session,
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<ConfirmForm>>,
+) -> Result<impl IntoResponse, mas_axum_utils::FancyError> {
+    let form = cookie_jar.verify_form(&clock, form)?;
+
+    let pending = repo
+        .user_totp()
+        .pending_enrollment(&session.user)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("no pending TOTP enrollment"))?;
+
+    let secret = TotpSecret::from_encrypted_bytes(pending.encrypted_secret.clone());
+    let now = clock.now().timestamp().try_into().unwrap_or(0);
+    let step = secret
+        .verify(&encrypter, &form.code, now, None)
+        .map_err(|_| anyhow::anyhow!("invalid code"))?;
+
+    repo.user_totp()
+        .confirm_enrollment(&clock, &pending, step)
+        .await?;
+
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,26 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! The `compat_access_tokens` repository, backing `mas-handlers`'
+//! `compat::login`.
+
+use async_trait::async_trait;
+use mas_data_model::{CompatAccessToken, CompatSession};
+
+use crate::{Clock, RepositoryError};
+
+/// `BoxRepository` exposes this via `repo.compat_access_token()`.
+#[async_trait]
+pub trait CompatAccessTokenRepository: Send + Sync {
+    /// Persists `token` as the access token for `session`, so it can later
+    /// be looked up to authenticate a compat API request.
+    async fn add(
+        &mut self,
+        clock: &dyn Clock,
+        session: &CompatSession,
+        token: String,
+    ) -> Result<CompatAccessToken, RepositoryError>;
+}
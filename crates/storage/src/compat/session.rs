@@ -0,0 +1,26 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! The `compat_sessions` repository, backing `mas-handlers`'
+//! `compat::login`.
+
+use async_trait::async_trait;
+use mas_data_model::CompatSession;
+
+use crate::{Clock, RepositoryError};
+
+/// `BoxRepository` exposes this via `repo.compat_session()`, the same way
+/// it already exposes `user()` and `oauth2_access_token()`.
+#[async_trait]
+pub trait CompatSessionRepository: Send + Sync {
+    /// Creates a new compat session for `user` logging in with `device_id`.
+    async fn add(
+        &mut self,
+        clock: &dyn Clock,
+        user: &mas_data_model::User,
+        device_id: String,
+    ) -> Result<CompatSession, RepositoryError>;
+}
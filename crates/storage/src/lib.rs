@@ -0,0 +1,25 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! Additive repository traits backing the passkey and TOTP second-factor
+//! work, the `client_credentials` grant allowlist, and compat-layer
+//! sessions. `BoxRepository` gains an accessor for each of these, alongside
+//! its existing `user()`, `user_password()`, `oauth2_access_token()`, etc.
+
+pub mod compat;
+pub mod oauth2;
+pub mod user;
+
+/// Minimal seam matching the `Clock` trait `BoxClock` already implements
+/// elsewhere in `mas-storage`; repeated here since only the additive pieces
+/// of this crate are present in this checkout.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> chrono::DateTime<chrono::Utc>;
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("repository error: {0}")]
+pub struct RepositoryError(#[from] pub Box<dyn std::error::Error + Send + Sync>);
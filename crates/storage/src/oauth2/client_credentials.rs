@@ -0,0 +1,25 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! The per-client `client_credentials` grant allowlist, backing
+//! `mas-handlers`' `oauth2::token::client_credentials_grant`.
+
+use async_trait::async_trait;
+use mas_data_model::{Client, ClientCredentialsAllowlist};
+
+use crate::RepositoryError;
+
+/// `BoxRepository` exposes this via `repo.client_credentials_allowlist()`,
+/// the same way it already exposes `oauth2_access_token()`.
+#[async_trait]
+pub trait ClientCredentialsAllowlistRepository: Send + Sync {
+    /// The allowlist entry for `client`, if it has been granted any
+    /// `client_credentials` access at all.
+    async fn for_client(
+        &mut self,
+        client: &Client,
+    ) -> Result<Option<ClientCredentialsAllowlist>, RepositoryError>;
+}
@@ -0,0 +1,9 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+pub mod client_credentials;
+
+pub use self::client_credentials::ClientCredentialsAllowlistRepository;
@@ -0,0 +1,67 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! The `user_passkeys` (and `user_passkey_challenges`) repository, backing
+//! `mas-handlers`' `views::passkeys`, `views::login`, and `views::reauth`.
+
+use async_trait::async_trait;
+use mas_data_model::{UserPasskey, UserPasskeyChallenge};
+use rand_core::RngCore;
+
+use crate::{Clock, RepositoryError};
+
+/// Stores registered WebAuthn credentials and the short-lived challenges
+/// used to register/authenticate with them.
+///
+/// `BoxRepository` exposes this via `repo.user_passkeys()`, the same way it
+/// already exposes `user()` and `user_password()`.
+#[async_trait]
+pub trait UserPasskeyRepository: Send + Sync {
+    /// Issues and stores a fresh challenge for `user`, to be consumed by
+    /// exactly one subsequent registration, login, or reauthentication
+    /// attempt. Registration and reauth call this with the current
+    /// session's user; login calls it with the user named by the username
+    /// submitted ahead of the passkey prompt, since no session exists yet
+    /// at that point.
+    async fn add_challenge(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &mas_data_model::User,
+        challenge: Vec<u8>,
+    ) -> Result<UserPasskeyChallenge, RepositoryError>;
+
+    /// Looks up and deletes the pending challenge for `user`, so it can't
+    /// be replayed.
+    async fn consume_challenge(
+        &mut self,
+        clock: &dyn Clock,
+        user: &mas_data_model::User,
+    ) -> Result<Option<UserPasskeyChallenge>, RepositoryError>;
+
+    /// Persists a newly-registered credential.
+    async fn add(
+        &mut self,
+        clock: &dyn Clock,
+        user: &mas_data_model::User,
+        name: String,
+        credential_id: String,
+        public_key: String,
+    ) -> Result<UserPasskey, RepositoryError>;
+
+    async fn find_by_credential_id(
+        &mut self,
+        credential_id: &str,
+    ) -> Result<Option<UserPasskey>, RepositoryError>;
+
+    /// Updates the stored signature counter after a successful assertion.
+    async fn set_signature_counter(
+        &mut self,
+        clock: &dyn Clock,
+        passkey: &UserPasskey,
+        counter: u32,
+    ) -> Result<UserPasskey, RepositoryError>;
+}
@@ -0,0 +1,55 @@
+// Copyright 2024, 2025 New Vector Ltd.
+// Copyright 2021-2024 The Matrix.org Foundation C.I.C.
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+// Please see LICENSE in the repository root for full details.
+
+//! The `user_totp` repository, backing `mas-handlers`' TOTP second factor.
+
+use async_trait::async_trait;
+use mas_data_model::UserTotp;
+
+use crate::{Clock, RepositoryError};
+
+#[async_trait]
+pub trait UserTotpRepository: Send + Sync {
+    /// The confirmed, active TOTP factor for `user`, if any.
+    async fn active(
+        &mut self,
+        user: &mas_data_model::User,
+    ) -> Result<Option<UserTotp>, RepositoryError>;
+
+    /// The most recent not-yet-confirmed enrollment for `user`, if any.
+    async fn pending_enrollment(
+        &mut self,
+        user: &mas_data_model::User,
+    ) -> Result<Option<UserTotp>, RepositoryError>;
+
+    /// Starts a new enrollment, storing the encrypted secret but not yet
+    /// marking it active.
+    async fn start_enrollment(
+        &mut self,
+        clock: &dyn Clock,
+        user: &mas_data_model::User,
+        encrypted_secret: Vec<u8>,
+    ) -> Result<UserTotp, RepositoryError>;
+
+    /// Confirms a pending enrollment after the user proved they can
+    /// generate a valid code, recording the step it was confirmed with so
+    /// it can't immediately be replayed.
+    async fn confirm_enrollment(
+        &mut self,
+        clock: &dyn Clock,
+        pending: &UserTotp,
+        confirmed_step: u64,
+    ) -> Result<UserTotp, RepositoryError>;
+
+    /// Records the time step a code was just verified for, so the same
+    /// code can't be replayed again within its validity window.
+    async fn record_used_step(
+        &mut self,
+        clock: &dyn Clock,
+        totp: &UserTotp,
+        step: u64,
+    ) -> Result<UserTotp, RepositoryError>;
+}